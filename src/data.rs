@@ -3,25 +3,181 @@ use std::{
 };
 
 use chrono::prelude::*;
+use chrono::Duration;
 use colored::*;
 use serde::{Deserialize, Serialize};
 use derivative::Derivative;
-use rusoto_core::Region;
 
 use std::rc::Rc;
 use std::cell::RefCell;
+use std::fmt;
 use std::str::FromStr;
 use std::collections::{HashMap, HashSet};
 
 use crate::{CfgKey};
-use crate::datasources::{AwsS3DataProviderFactory, LocalDataProvider, DataProviderFactory};
+use crate::datasources::{AwsS3DataProviderFactory, AzureBlobDataProviderFactory, GoogleCloudStorageDataProviderFactory, LocalDataProvider, DataProvider, DataProviderFactory, provider_from_uri};
+
+/// The default history format for entries in the current year.
+const DEFAULT_TIME_FORMAT:&str = "%b %d %I:%M%P";
+/// The default history format for entries in a previous year (includes the year).
+const DEFAULT_TIME_FORMAT_LONG:&str = "%b %d %Y %I:%M%P";
+
+/// Which clock history timestamps are rendered in.
+pub enum Timezone {
+    Local,
+    Utc,
+    /// A fixed offset east of UTC, in seconds.
+    Offset(i32),
+}
+
+/// Resolved timestamp display preferences threaded into [`HistoryItem::print`].
+pub struct DisplaySettings {
+    pub timezone:Timezone,
+    pub format_same_year:String,
+    pub format_other_year:String,
+}
+
+/// A typed conversion applied to a raw configuration string. Each [`CfgKey`]
+/// declares the conversion it expects so bad input is rejected with a
+/// structured error instead of a panic.
+#[derive(Debug, PartialEq, Clone)]
+pub enum Conversion {
+    Bytes,
+    String,
+    Integer,
+    Float,
+    Boolean,
+    Timestamp,
+    TimestampFmt(String),
+}
+
+/// A value produced by a [`Conversion`].
+#[derive(Debug, PartialEq, Clone)]
+pub enum ConfigValue {
+    Bytes(Vec<u8>),
+    String(String),
+    Integer(i64),
+    Float(f32),
+    Boolean(bool),
+    Timestamp(u64),
+}
+
+impl ConfigValue {
+    /// The value as a float, when it was produced by a float conversion.
+    pub fn as_float(&self) -> Option<f32> {
+        match self {
+            ConfigValue::Float(value) => Some(*value),
+            _ => None,
+        }
+    }
+
+    /// The value as an integer, when it was produced by an integer conversion.
+    pub fn as_integer(&self) -> Option<i64> {
+        match self {
+            ConfigValue::Integer(value) => Some(*value),
+            _ => None,
+        }
+    }
+}
+
+/// An error surfaced by the configuration layer so the CLI can print a clean
+/// message rather than aborting.
+#[derive(Debug)]
+pub enum ConfigError {
+    UnknownConversion,
+    Parse(Conversion),
+    WrongArgCount(String),
+    InvalidProvider(String),
+}
+
+impl fmt::Display for Conversion {
+    fn fmt(&self, f:&mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Conversion::Bytes => write!(f, "bytes"),
+            Conversion::String => write!(f, "string"),
+            Conversion::Integer => write!(f, "integer"),
+            Conversion::Float => write!(f, "float"),
+            Conversion::Boolean => write!(f, "boolean"),
+            Conversion::Timestamp => write!(f, "timestamp"),
+            Conversion::TimestampFmt(format) => write!(f, "timestamp ({})", format),
+        }
+    }
+}
+
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f:&mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ConfigError::UnknownConversion => write!(f, "Unknown conversion"),
+            ConfigError::Parse(conversion) => write!(f, "could not parse {}", conversion),
+            ConfigError::WrongArgCount(message) => write!(f, "{}", message),
+            ConfigError::InvalidProvider(provider) => write!(f, "Invalid provider \"{}\", valid are local, aws, gcs or azure", provider),
+        }
+    }
+}
+
+impl FromStr for Conversion {
+    type Err = ConfigError;
+    fn from_str(string:&str) -> Result<Self, Self::Err> {
+        return match string.trim().to_ascii_lowercase().as_str() {
+            "bytes" => Ok(Conversion::Bytes),
+            "string" | "str" => Ok(Conversion::String),
+            "int" | "integer" => Ok(Conversion::Integer),
+            "float" => Ok(Conversion::Float),
+            "bool" | "boolean" => Ok(Conversion::Boolean),
+            "timestamp" => Ok(Conversion::Timestamp),
+            _ => Err(ConfigError::UnknownConversion),
+        };
+    }
+}
+
+impl Conversion {
+    /// Parse a raw string into the typed [`ConfigValue`] this conversion names,
+    /// returning a [`ConfigError`] on failure.
+    pub fn convert(&self, raw:&str) -> Result<ConfigValue, ConfigError> {
+        return match self {
+            Conversion::Bytes => Ok(ConfigValue::Bytes(raw.as_bytes().to_vec())),
+            Conversion::String => Ok(ConfigValue::String(raw.to_string())),
+            Conversion::Integer => raw.parse::<i64>().map(ConfigValue::Integer).map_err(|_| ConfigError::Parse(self.clone())),
+            Conversion::Float => raw.parse::<f32>().map(ConfigValue::Float).map_err(|_| ConfigError::Parse(self.clone())),
+            Conversion::Boolean => raw.parse::<bool>().map(ConfigValue::Boolean).map_err(|_| ConfigError::Parse(self.clone())),
+            Conversion::Timestamp => raw.parse::<u64>().map(ConfigValue::Timestamp).map_err(|_| ConfigError::Parse(self.clone())),
+            Conversion::TimestampFmt(format) => NaiveDateTime::parse_from_str(raw, format)
+                .map(|date| ConfigValue::Timestamp(date.timestamp_millis() as u64))
+                .map_err(|_| ConfigError::Parse(self.clone())),
+        };
+    }
+}
 
 #[derive(Serialize, Deserialize, Clone)]
 pub struct Config {
     data_source:Option<DataSource>,
     pub local_data_source:Option<Rc<RefCell<LocalDataProvider>>>,
     pub aws_data_source:Option<Rc<RefCell<AwsS3DataProviderFactory>>>,
-    pub use_local:Option<bool>
+    #[serde(default)]
+    pub gcs_data_source:Option<Rc<RefCell<GoogleCloudStorageDataProviderFactory>>>,
+    #[serde(default)]
+    pub azure_data_source:Option<Rc<RefCell<AzureBlobDataProviderFactory>>>,
+    pub use_local:Option<bool>,
+    /// Selected backend kind (`local`, `aws`, `gcs`, `azure`). Supersedes the
+    /// legacy `use_local` flag when set.
+    #[serde(default)]
+    pub provider:Option<String>,
+    /// A single data-location URI (`file:///...` or `s3://bucket/prefix`) that,
+    /// when set, selects the backend directly and supersedes `use_local`,
+    /// `provider` and the per-backend factory selection. An `s3://` location
+    /// still takes credentials, region, endpoint and profile from the AWS factory.
+    #[serde(default)]
+    pub location:Option<String>,
+    /// Timezone history is rendered in (`local`, `utc`, or a fixed offset like
+    /// `+02:00`). Falls back to `local` when unset.
+    #[serde(default)]
+    pub timezone:Option<String>,
+    /// `strftime` format for history entries in the current year.
+    #[serde(default)]
+    pub time_format:Option<String>,
+    /// `strftime` format for history entries in a previous year.
+    #[serde(default)]
+    pub time_format_long:Option<String>
 }
 
 #[derive(Serialize, Deserialize, PartialEq, Clone)]
@@ -32,18 +188,58 @@ enum DataSource {
 
 impl Config {
     pub fn new() -> Config {
-        return Config {data_source:None, local_data_source:None, aws_data_source:None, use_local:None};
+        return Config {data_source:None, local_data_source:None, aws_data_source:None, gcs_data_source:None, azure_data_source:None, use_local:None, provider:None, location:None, timezone:None, time_format:None, time_format_long:None};
+    }
+
+    /// Resolve the timestamp display preferences, falling back to the historic
+    /// `Local` behavior and default formats when they are unset.
+    pub fn display_settings(&self) -> DisplaySettings {
+        let timezone = match self.timezone.as_deref().map(|tz| tz.trim().to_ascii_lowercase()) {
+            Some(ref tz) if tz == "local" => Timezone::Local,
+            Some(ref tz) if tz == "utc" => Timezone::Utc,
+            Some(ref tz) => Timezone::Offset(parse_offset_seconds(tz).unwrap_or(0)),
+            None => Timezone::Local,
+        };
+        return DisplaySettings {
+            timezone,
+            format_same_year:self.time_format.clone().unwrap_or_else(|| DEFAULT_TIME_FORMAT.to_string()),
+            format_other_year:self.time_format_long.clone().unwrap_or_else(|| DEFAULT_TIME_FORMAT_LONG.to_string()),
+        };
+    }
+
+    /// Resolve the data provider, preferring the unified `location` URI when set
+    /// and otherwise falling back to the legacy per-backend factories.
+    pub fn get_provider(&mut self) -> Result<Rc<dyn DataProvider>, String> {
+        if let Some(location) = self.location.clone() {
+            let aws = self.get_aws();
+            let aws = aws.borrow();
+            return provider_from_uri(&location, &aws);
+        }
+        return Ok(self.get_provider_factory().borrow().to_provider());
     }
 
     pub fn get_provider_factory(&mut self) -> Rc<RefCell<dyn DataProviderFactory>> {
         self.convert_from_datasource();
-        if self.use_local() {
-            return self.get_local()
-        } else {
-            return self.get_aws();
+        match self.selected_provider().as_str() {
+            "aws" => self.get_aws() as Rc<RefCell<dyn DataProviderFactory>>,
+            "gcs" => self.get_gcs() as Rc<RefCell<dyn DataProviderFactory>>,
+            "azure" => self.get_azure() as Rc<RefCell<dyn DataProviderFactory>>,
+            _ => self.get_local() as Rc<RefCell<dyn DataProviderFactory>>,
         }
     }
 
+    /// The selected backend kind, preferring the explicit `provider` setting and
+    /// falling back to the legacy `use_local` flag.
+    pub fn selected_provider(&self) -> String {
+        if let Some(provider) = &self.provider {
+            return provider.clone();
+        }
+        if self.use_local.unwrap_or(true) {
+            return "local".to_string();
+        }
+        return "aws".to_string();
+    }
+
     /// Old system used the datasource enum, but we want to stop that
     fn convert_from_datasource (&mut self) {
         if self.data_source.is_some() {
@@ -59,14 +255,6 @@ impl Config {
         }
     }
 
-    fn use_local(&mut self) -> bool {
-        if self.use_local.is_some() {
-            return self.use_local.unwrap();
-        } else {
-            return true;
-        }
-    }
-
     pub fn get_local(&mut self) -> Rc<RefCell<LocalDataProvider>> {
         self.convert_from_datasource();
         if self.local_data_source.is_none() {
@@ -82,6 +270,22 @@ impl Config {
         }
         return self.aws_data_source.clone().unwrap();
     }
+
+    pub fn get_gcs(&mut self) -> Rc<RefCell<GoogleCloudStorageDataProviderFactory>> {
+        self.convert_from_datasource();
+        if self.gcs_data_source.is_none() {
+            self.gcs_data_source = Some(Rc::new(RefCell::new(GoogleCloudStorageDataProviderFactory::new())));
+        }
+        return self.gcs_data_source.clone().unwrap();
+    }
+
+    pub fn get_azure(&mut self) -> Rc<RefCell<AzureBlobDataProviderFactory>> {
+        self.convert_from_datasource();
+        if self.azure_data_source.is_none() {
+            self.azure_data_source = Some(Rc::new(RefCell::new(AzureBlobDataProviderFactory::new())));
+        }
+        return self.azure_data_source.clone().unwrap();
+    }
 }
 #[derive(Derivative, Serialize, Deserialize, Clone, Debug)]
 #[derivative(PartialEq)]
@@ -98,7 +302,20 @@ pub struct Data {
     #[serde(default)]
     cringe_factors:HashMap<String, f32>,
     #[serde(default)]
-    synonyms:HashMap<String, HashSet<String>>
+    synonyms:HashMap<String, HashSet<String>>,
+    #[serde(default)]
+    recurring:Vec<RecurringItem>
+}
+
+/// A scheduled charge (rent, subscription, allowance) that [`Data::update`]
+/// applies automatically, catching up on every period that elapsed since
+/// `last_applied`.
+#[derive(Serialize, Deserialize, PartialEq, Clone, Debug)]
+pub struct RecurringItem {
+    amount:f32,
+    reason:String,
+    period_days:u32,
+    last_applied:u64
 }
 
 impl Data {
@@ -112,7 +329,8 @@ impl Data {
             rate:Some(5.),
             last_updated:Local::now().timestamp_millis() as u64,
             cringe_factors:HashMap::new(),
-            synonyms:HashMap::new()
+            synonyms:HashMap::new(),
+            recurring:vec![]
         }
     }
 
@@ -136,9 +354,47 @@ impl Data {
             }
         }
         self.balance = self.balance + net_gains;
+
+        // Back-fill every scheduled charge that came due since it was last applied.
+        for idx in 0..self.recurring.len() {
+            let amount = self.recurring[idx].amount;
+            let reason = self.recurring[idx].reason.clone();
+            let period_days = self.recurring[idx].period_days;
+            let last_applied = self.recurring[idx].last_applied;
+            if period_days == 0 {
+                continue;
+            }
+            let last_applied_day = Local.timestamp_millis(last_applied as i64).num_days_from_ce();
+            let elapsed_days = current - last_applied_day;
+            if elapsed_days <= 0 {
+                continue;
+            }
+            let periods = elapsed_days as u32 / period_days;
+            if periods == 0 {
+                continue;
+            }
+            for period in 1..=periods {
+                let time = Local.timestamp_millis(last_applied as i64) + Duration::days((period * period_days) as i64);
+                self.history.push(HistoryItem{amount, reason:reason.clone(), specific:None, time:time.timestamp_millis() as u64});
+            }
+            self.balance -= periods as f32 * amount;
+            // Advance by exactly the applied periods so partial periods carry over.
+            self.recurring[idx].last_applied = (Local.timestamp_millis(last_applied as i64) + Duration::days((periods * period_days) as i64)).timestamp_millis() as u64;
+        }
+
         self.last_updated = now.timestamp_millis() as u64;
     }
 
+    pub fn add_recurring(&mut self, amount:f32, reason:String, period_days:u32) {
+        let item = RecurringItem{amount, reason, period_days, last_applied:Local::now().timestamp_millis() as u64};
+        self.recurring.push(item);
+    }
+
+    pub fn remove_recurring(&mut self, reason:&dyn AsRef<str>) {
+        let reason = reason.as_ref().to_ascii_lowercase();
+        self.recurring.retain(|item| item.reason.to_ascii_lowercase() != reason);
+    }
+
     pub fn set_cringe(&mut self, keyword:&dyn AsRef<str>, factor:f32) {
         let keyword = &keyword.as_ref().to_ascii_lowercase();
         if self.has_synonyms(keyword) {
@@ -195,9 +451,97 @@ impl Data {
         return self.synonyms.contains_key(&key);
     }
 
-    /// The balance minus the debts
-    pub fn total_balance(&self) -> f32{
-        return self.balance - self.debt;
+    /// Three-way merge of this (local) data with a `remote` copy for multi-device
+    /// sync. The shared longest common prefix of the two histories is kept
+    /// untouched; the divergent suffix on each side is treated as independent new
+    /// entries, concatenated and ordered by time. The balance is recomputed from
+    /// the pre-divergence balance by re-subtracting every merged entry; debt is
+    /// not derived from history and is taken from the more recently updated side.
+    /// The `cringe_factors`/`synonyms` maps are unioned (last-writer-wins by
+    /// `last_updated` on conflicting keys). Returns the merged data alongside a
+    /// report of which entries came from which side.
+    pub fn merge(&self, remote:&Data) -> (Data, MergeReport) {
+        // Longest common prefix of the two histories.
+        let mut prefix_len = 0;
+        while prefix_len < self.history.len() && prefix_len < remote.history.len() && self.history[prefix_len] == remote.history[prefix_len] {
+            prefix_len += 1;
+        }
+
+        let local_suffix = &self.history[prefix_len..];
+        let remote_suffix = &remote.history[prefix_len..];
+
+        // Keep the common prefix, then the divergent suffixes ordered by time.
+        let mut merged_history:Vec<HistoryItem> = self.history[..prefix_len].to_vec();
+        let mut divergent:Vec<HistoryItem> = local_suffix.to_vec();
+        divergent.extend_from_slice(remote_suffix);
+        divergent.sort_by_key(|item| item.time);
+        merged_history.extend(divergent);
+
+        // Recompute the balance from the balance before either side diverged.
+        let local_suffix_sum:f32 = local_suffix.iter().map(|item| item.amount).sum();
+        let remote_suffix_sum:f32 = remote_suffix.iter().map(|item| item.amount).sum();
+        let pre_divergence_balance = self.balance + local_suffix_sum;
+        let merged_balance = pre_divergence_balance - (local_suffix_sum + remote_suffix_sum);
+
+        // Union the keyword maps, letting the more recently updated side win.
+        let (primary, secondary) = if self.last_updated >= remote.last_updated {(self, remote)} else {(remote, self)};
+        let mut cringe_factors = secondary.cringe_factors.clone();
+        for (keyword, factor) in &primary.cringe_factors {
+            cringe_factors.insert(keyword.clone(), *factor);
+        }
+        let mut synonyms = secondary.synonyms.clone();
+        for (keyword, set) in &primary.synonyms {
+            synonyms.insert(keyword.clone(), set.clone());
+        }
+
+        let report = MergeReport {
+            entries: self.history[..prefix_len].iter().map(|item| (MergeSource::Common, item.clone()))
+                .chain(local_suffix.iter().map(|item| (MergeSource::OnlyInLocal, item.clone())))
+                .chain(remote_suffix.iter().map(|item| (MergeSource::OnlyInRemote, item.clone())))
+                .collect(),
+        };
+
+        let merged = Data {
+            version: std::cmp::max(self.version.map(|v| v as u64), remote.version.map(|v| v as u64)).map(|v| v as f32),
+            history: merged_history,
+            redo_stack: self.redo_stack.clone(),
+            balance: merged_balance,
+            // Debt is not derivable from the merged history, so take it from the
+            // more recently updated side (last-writer-wins, like the keyword maps).
+            // Keeping the larger would resurrect debt a repayment on either device
+            // had already cleared.
+            debt: primary.debt,
+            last_updated: std::cmp::max(self.last_updated, remote.last_updated),
+            rate: self.rate.or(remote.rate),
+            cringe_factors,
+            synonyms,
+            recurring: primary.recurring.clone(),
+        };
+        return (merged, report);
+    }
+}
+
+/// Which side of a [`Data::merge`] a history entry came from.
+#[derive(Debug, PartialEq, Clone)]
+pub enum MergeSource {
+    Common,
+    OnlyInLocal,
+    OnlyInRemote,
+}
+
+/// A record of how each history entry was classified during [`Data::merge`].
+pub struct MergeReport {
+    pub entries:Vec<(MergeSource, HistoryItem)>
+}
+
+impl MergeReport {
+    /// Print a short summary of how many entries came from each side.
+    pub fn print(&self) {
+        let only_local = self.entries.iter().filter(|(source, _)| *source == MergeSource::OnlyInLocal).count();
+        let only_remote = self.entries.iter().filter(|(source, _)| *source == MergeSource::OnlyInRemote).count();
+        if only_local > 0 || only_remote > 0 {
+            println!("Merged {} local and {} remote new entries", only_local, only_remote);
+        }
     }
 }
 
@@ -215,6 +559,19 @@ pub struct HistoryItem {
     time:u64
 }
 
+/// Parse a `+HH:MM`/`-HH:MM` (or bare signed hour) UTC offset into seconds east
+/// of UTC.
+fn parse_offset_seconds(offset:&str) -> Option<i32> {
+    let (sign, rest) = match offset.strip_prefix('-') {
+        Some(rest) => (-1, rest),
+        None => (1, offset.strip_prefix('+').unwrap_or(offset)),
+    };
+    let mut parts = rest.split(':');
+    let hours:i32 = parts.next()?.parse().ok()?;
+    let minutes:i32 = parts.next().map(|m| m.parse().ok()).unwrap_or(Some(0))?;
+    return Some(sign * (hours * 3600 + minutes * 60));
+}
+
 fn format_dollars(amount:&f32) -> String {
     let sign_string = if amount < &0. {"-"} else {""};
     let result = format!("{}${:.2}", sign_string, amount.abs());
@@ -222,17 +579,28 @@ fn format_dollars(amount:&f32) -> String {
 }
 
 impl HistoryItem {
-    fn print(&self) {
-        let current_year = Local::now().year();
-        let date = Local.timestamp_millis(self.time as i64);
-        let item_year = date.year();
-        let format_str;
-        if current_year == item_year {
-            format_str = "%b %d %I:%M%P"
-        } else {
-            format_str = "%b %d %Y %I:%M%P"
-        }
-        println!("{}: {} {} {}", date.format(format_str).to_string().blue().on_black(), format_dollars(&self.amount).bright_red().on_black(), self.reason.yellow().on_black(), 
+    fn print(&self, display:&DisplaySettings) {
+        // Render the timestamp in the configured zone, choosing the same-year or
+        // other-year format by comparing against "now" in that same zone.
+        let date_string = match display.timezone {
+            Timezone::Local => {
+                let date = Local.timestamp_millis(self.time as i64);
+                let format_str = if Local::now().year() == date.year() {&display.format_same_year} else {&display.format_other_year};
+                date.format(format_str).to_string()
+            },
+            Timezone::Utc => {
+                let date = Utc.timestamp_millis(self.time as i64);
+                let format_str = if Utc::now().year() == date.year() {&display.format_same_year} else {&display.format_other_year};
+                date.format(format_str).to_string()
+            },
+            Timezone::Offset(seconds) => {
+                let zone = FixedOffset::east_opt(seconds).unwrap_or_else(|| FixedOffset::east_opt(0).unwrap());
+                let date = zone.timestamp_millis(self.time as i64);
+                let format_str = if Utc::now().with_timezone(&zone).year() == date.year() {&display.format_same_year} else {&display.format_other_year};
+                date.format(format_str).to_string()
+            },
+        };
+        println!("{}: {} {} {}", date_string.blue().on_black(), format_dollars(&self.amount).bright_red().on_black(), self.reason.yellow().on_black(),
             if self.specific.is_some() {
                 format!("({})", self.specific.as_ref().unwrap())
             } else {
@@ -249,8 +617,9 @@ pub struct Budget {
 
 impl Budget {
     pub fn list(&self) {
+        let display = self.config.display_settings();
         for item in &self.data.history {
-            item.print();
+            item.print(&display);
         }
         self.print_balance();
     }
@@ -260,7 +629,7 @@ impl Budget {
             panic!("History is empty")
         }
         let last_item = self.data.history.pop().unwrap();
-        last_item.print();
+        last_item.print(&self.config.display_settings());
         let amount = last_item.amount;
         self.data.balance += amount;
         self.data.redo_stack.push(last_item);
@@ -272,7 +641,7 @@ impl Budget {
             panic!("Redo stack is empty")
         }
         let last_item = self.data.redo_stack.pop().unwrap();
-        last_item.print();
+        last_item.print(&self.config.display_settings());
         let amount = last_item.amount;
         self.data.balance -= amount;
         self.data.history.push(last_item);
@@ -306,7 +675,7 @@ impl Budget {
             println!("Balance: {}", format_dollars(&self.data.balance).bright_red().on_black());
         } else {
             let history_item = HistoryItem{amount:amount_scaled, reason:reason, specific:specific, time:Local::now().timestamp_millis() as u64};
-            history_item.print();
+            history_item.print(&self.config.display_settings());
             self.data.history.push(history_item);
             self.data.balance = new_balance;
             let balance_formatted = if new_balance<0. {format_dollars(&new_balance).bright_red().on_black()} else {format_dollars(&new_balance).green().on_black()};
@@ -314,11 +683,31 @@ impl Budget {
         }
     }
     
-    pub fn set_cfg(&mut self, key:&CfgKey, values:&Vec<String>) {
+    pub fn set_cfg(&mut self, key:&CfgKey, values:&Vec<String>) -> Result<(), ConfigError> {
+        // Every key needs at least one value; reject a bare `set <key>` with a
+        // clean error rather than indexing into an empty vector.
+        if values.is_empty() {
+            return Err(ConfigError::WrongArgCount(format!("set {} needs at least one value", key.to_string().to_lowercase())));
+        }
         let value = values[0].clone();
+        // `set recurring <reason> remove` carries a sentinel in place of the
+        // numeric arguments the declared conversions expect, so handle it before
+        // converting anything.
+        if let CfgKey::Recurring = key {
+            if values.len() == 2 && values[1].eq_ignore_ascii_case("remove") {
+                self.remove_recurring(&values[0].clone());
+                return Ok(());
+            }
+        }
+        // Validate every positional value against the conversion its key declares
+        // up front so bad input is rejected before anything is mutated.
+        let converted:Vec<ConfigValue> = values.iter()
+            .zip(key.conversions().iter())
+            .map(|(value, conversion)| conversion.convert(value))
+            .collect::<Result<_, _>>()?;
         match key {
             CfgKey::Rate => {
-                self.data.rate = Some(value.parse::<f32>().unwrap());
+                self.data.rate = converted[0].as_float();
                 self.print_rate();
             },
             CfgKey::Path => {
@@ -337,62 +726,129 @@ impl Budget {
             CfgKey::SecretKey => {
                 let provider = Rc::clone(&self.config.get_aws());
                 let mut provider = provider.borrow_mut();
-                provider.bucket_name = value.clone();
+                provider.secret_access_key = value.clone();
                 println!("Secret key: {}", provider.secret_access_key)
 
             },
             CfgKey::BucketName => {
+                // Route the bucket/container to whichever cloud backend is selected.
+                match self.config.selected_provider().as_str() {
+                    "gcs" => {
+                        let provider = Rc::clone(&self.config.get_gcs());
+                        let mut provider = provider.borrow_mut();
+                        provider.bucket_name = value.clone();
+                        println!("Bucket name: {}", provider.bucket_name)
+                    },
+                    "azure" => {
+                        let provider = Rc::clone(&self.config.get_azure());
+                        let mut provider = provider.borrow_mut();
+                        provider.container_name = value.clone();
+                        println!("Container name: {}", provider.container_name)
+                    },
+                    _ => {
+                        let provider = Rc::clone(&self.config.get_aws());
+                        let mut provider = provider.borrow_mut();
+                        provider.bucket_name = value.clone();
+                        println!("Bucket name: {}", provider.bucket_name)
+                    },
+                }
+            },
+            CfgKey::Region => {
                 let provider = Rc::clone(&self.config.get_aws());
                 let mut provider = provider.borrow_mut();
-                provider.access_key = value.clone();
-                println!("Bucket name: {}", provider.bucket_name)
-
+                provider.region = value.clone();
+                println!("Region: {}", provider.region)
             },
-            CfgKey::Region => {
+            CfgKey::Endpoint => {
+                let provider = Rc::clone(&self.config.get_aws());
+                let mut provider = provider.borrow_mut();
+                provider.endpoint = if value.is_empty() {None} else {Some(value.clone())};
+                println!("Endpoint: {}", provider.endpoint.as_deref().unwrap_or("(none)"))
+            },
+            CfgKey::Profile => {
                 let provider = Rc::clone(&self.config.get_aws());
                 let mut provider = provider.borrow_mut();
-                provider.region = Region::from_str(value.as_str()).expect("Invalid region");
-                println!("Region: {:?}", provider.region)
+                provider.profile = if value.is_empty() {None} else {Some(value.clone())};
+                println!("Profile: {}", provider.profile.as_deref().unwrap_or("(none)"))
+            },
+            CfgKey::Location => {
+                self.config.location = if value.is_empty() {None} else {Some(value.clone())};
+                println!("Location: {}", self.config.location.as_deref().unwrap_or("(none)"))
+            },
+            CfgKey::TimeFormat => {
+                // First value sets the current-year format, an optional second the previous-year one.
+                self.config.time_format = if value.is_empty() {None} else {Some(value.clone())};
+                if values.len() >= 2 {
+                    self.config.time_format_long = Some(values[1].clone());
+                }
+                println!("Time format: {}", self.config.time_format.as_deref().unwrap_or("(default)"))
+            },
+            CfgKey::Timezone => {
+                self.config.timezone = if value.is_empty() {None} else {Some(value.clone())};
+                println!("Timezone: {}", self.config.timezone.as_deref().unwrap_or("local"))
             },
             CfgKey::Provider => {
                 match value.trim().to_lowercase().as_str() {
-                    "aws" => {
-                        self.config.use_local = Some(false);
-                        println!("Provider set to AWS");
-                    },
-                    "local" => {
-                        self.config.use_local = Some(true);
-                        println!("Provider set to local");
+                    kind @ ("aws" | "local" | "gcs" | "azure") => {
+                        self.config.provider = Some(kind.to_string());
+                        // Keep the legacy flag consistent for older readers.
+                        self.config.use_local = Some(kind == "local");
+                        println!("Provider set to {}", kind);
                     },
                     _=>{
-                        panic!("Invalid provider \"{}\", valid are aws or local", value)
+                        return Err(ConfigError::InvalidProvider(value));
                     }
                 }
             },
+            CfgKey::ServiceAccount => {
+                let provider = Rc::clone(&self.config.get_gcs());
+                let mut provider = provider.borrow_mut();
+                provider.service_account = if value.is_empty() {None} else {Some(value.clone())};
+                println!("Service account: {}", provider.service_account.as_deref().unwrap_or("(none)"))
+            },
+            CfgKey::ConnectionString => {
+                let provider = Rc::clone(&self.config.get_azure());
+                let mut provider = provider.borrow_mut();
+                provider.connection_string = value.clone();
+                println!("Connection string: {}", provider.connection_string)
+            },
             CfgKey::Cringe => {
                 if values.len() != 2 {
-                    panic!("Wrong number of arguments to cringe")
+                    return Err(ConfigError::WrongArgCount("Wrong number of arguments to cringe".to_string()));
                 }
-                
+
                 let cringe_keyword = values[0].clone();
-                let cringe_factor = f32::from_str(&values[1]);
+                let cringe_factor = converted[1].as_float().unwrap();
 
-                self.data.set_cringe(&cringe_keyword, cringe_factor.unwrap());
+                self.data.set_cringe(&cringe_keyword, cringe_factor);
             },
             CfgKey::Synonym => {
 
                 if values.len() != 2 {
-                    panic!("Wrong number of arguments to Synonym")
+                    return Err(ConfigError::WrongArgCount("Wrong number of arguments to Synonym".to_string()));
                 }
                 let first = values[0].clone();
                 let second = values[1].clone();
 
                 self.data.set_synonym(&first, &second);
+            },
+            CfgKey::Recurring => {
+                // The `remove` form was handled before conversion above, so here
+                // only `set recurring <reason> <amount> <period_days>` remains.
+                if values.len() == 3 {
+                    let reason = values[0].clone();
+                    let amount = converted[1].as_float().unwrap();
+                    let period_days = converted[2].as_integer().unwrap() as u32;
+                    self.add_recurring(amount, reason, period_days);
+                } else {
+                    return Err(ConfigError::WrongArgCount("Usage: set recurring <reason> <amount> <period_days> | set recurring <reason> remove".to_string()));
+                }
             }
         }
+        return Ok(());
     }
 
-    pub fn get_cfg(&mut self, key:&CfgKey) {
+    pub fn get_cfg(&mut self, key:&CfgKey) -> Result<(), ConfigError> {
         match key {
             CfgKey::Rate => {
                 self.print_rate();
@@ -415,31 +871,83 @@ impl Budget {
 
             },
             CfgKey::BucketName => {
+                match self.config.selected_provider().as_str() {
+                    "gcs" => {
+                        let provider = Rc::clone(&self.config.get_gcs());
+                        let provider = provider.borrow();
+                        println!("Bucket name: {}", provider.bucket_name)
+                    },
+                    "azure" => {
+                        let provider = Rc::clone(&self.config.get_azure());
+                        let provider = provider.borrow();
+                        println!("Container name: {}", provider.container_name)
+                    },
+                    _ => {
+                        let provider = Rc::clone(&self.config.get_aws());
+                        let provider = provider.borrow();
+                        println!("Bucket name: {}", provider.bucket_name)
+                    },
+                }
+            },
+            CfgKey::Region => {
                 let provider = Rc::clone(&self.config.get_aws());
                 let provider = provider.borrow();
-                println!("Bucket name: {}", provider.bucket_name)
-
+                println!("Region: {}", provider.region)
             },
-            CfgKey::Region => {
+            CfgKey::Endpoint => {
+                let provider = Rc::clone(&self.config.get_aws());
+                let provider = provider.borrow();
+                println!("Endpoint: {}", provider.endpoint.as_deref().unwrap_or("(none)"))
+            },
+            CfgKey::Profile => {
                 let provider = Rc::clone(&self.config.get_aws());
                 let provider = provider.borrow();
-                println!("Region: {:?}", provider.region)
+                println!("Profile: {}", provider.profile.as_deref().unwrap_or("(none)"))
+            },
+            CfgKey::Location => {
+                println!("Location: {}", self.config.location.as_deref().unwrap_or("(none)"))
+            },
+            CfgKey::TimeFormat => {
+                println!("Time format: {} / {}", self.config.time_format.as_deref().unwrap_or("(default)"), self.config.time_format_long.as_deref().unwrap_or("(default)"))
+            },
+            CfgKey::Timezone => {
+                println!("Timezone: {}", self.config.timezone.as_deref().unwrap_or("local"))
             },
             CfgKey::Provider => {
-                if self.config.use_local() {
-                    println!("Provider set to local");
-                } else{
-                    println!("Provider set to AWS");
-                }
+                println!("Provider set to {}", self.config.selected_provider());
+            },
+            CfgKey::ServiceAccount => {
+                let provider = Rc::clone(&self.config.get_gcs());
+                let provider = provider.borrow();
+                println!("Service account: {}", provider.service_account.as_deref().unwrap_or("(none)"))
+            },
+            CfgKey::ConnectionString => {
+                let provider = Rc::clone(&self.config.get_azure());
+                let provider = provider.borrow();
+                println!("Connection string: {}", provider.connection_string)
             },
             CfgKey::Cringe => {
                 println!("{:?}", &self.data.cringe_factors);
             },
             CfgKey::Synonym => {
                 println!("{:?}", &self.data.synonyms);
+            },
+            CfgKey::Recurring => {
+                println!("{:?}", &self.data.recurring);
             }
 
         }
+        return Ok(());
+    }
+
+    pub fn add_recurring(&mut self, amount:f32, reason:String, period_days:u32) {
+        self.data.add_recurring(amount, reason.clone(), period_days);
+        println!("Added recurring {} of {} every {} days", reason.yellow().on_black(), format_dollars(&amount).bright_red().on_black(), period_days);
+    }
+
+    pub fn remove_recurring(&mut self, reason:&dyn AsRef<str>) {
+        self.data.remove_recurring(reason);
+        println!("Removed recurring {}", reason.as_ref().yellow().on_black());
     }
 
     pub fn print_rate(&self) {
@@ -452,58 +960,121 @@ impl Budget {
         println!("Balance:\t{}\nDebt:\t\t{}", balance_formatted, debt_formatted);
     }
 
-    pub fn verify_against(&self, old_data:Data) -> bool{
-        let mut old_data_updated = old_data.clone();
-        old_data_updated.rate = self.data.rate;
-        old_data_updated.update(&old_data_updated.rate.unwrap());
-        if self.data == old_data_updated {
-            return true;
-        }
-        if (old_data_updated.history.len() as i32 - self.data.history.len() as i32).abs() > 2 || (old_data_updated.redo_stack.len() as i32 - self.data.redo_stack.len() as i32).abs() > 2 {
-            // histories are too different
-            println!("{}", "Histories diverge by more than one entry".red().on_black());
-            return false;
-        }
-        if old_data_updated.history.len() > 0 && old_data_updated.history.len() > self.data.history.len() {
-            if  &old_data_updated.history[..old_data_updated.history.len()-1] == &self.data.history[..] {
-                // everything matches except we have one more entry in the old data, EG we must have undone something
-                let last_item = old_data_updated.history.last().unwrap();
-                old_data_updated.balance += last_item.amount;
-                if self.data.total_balance() == old_data_updated.total_balance() {
-                    return true;
-                } else {
-                    println!("{}", format!("Data missing entry but old data history does not match (expected {} but found {})", self.data.balance, old_data_updated.balance).red().on_black());
-                    return false;
-                }
-                // // revert
-                // old_data_updated.balance -= last_item.amount;
-            } else {
-                println!("{}", "Histories are incompatible".red().on_black());
-                return false;
-            }
-        } else if self.data.history.len() > 0 && self.data.history.len() > old_data_updated.history.len() {
-            if  &self.data.history[..self.data.history.len()-1] == &old_data_updated.history[..] {
-                // everything matches except we have one more entry in the new data, EG we must have added something
-                let last_item = self.data.history.last().unwrap();
-                old_data_updated.balance -= last_item.amount;
-                if self.data.total_balance() == old_data_updated.total_balance() {
-                    return true;
-                } else {
-                    println!("{}", format!("Data has new entry but diverges from old data (expected {} but found {})", self.data.balance, old_data_updated.balance).red().on_black());
-                    return false;
-                }
-                // // revert
-                // old_data_updated.balance += last_item.amount;
-            } else {
-                println!("{}", "Histories are incompatible".red().on_black());
-                return false;
-            }
+    /// Reconcile the local data against the `remote` copy read back from the
+    /// data provider. When both sides carry entries the other lacks, the local
+    /// data is replaced with the three-way merge so concurrent edits on two
+    /// devices are combined rather than rejected; when the local history is a
+    /// prefix of the remote one, the remote copy is taken; otherwise the local
+    /// data is kept.
+    pub fn merge_against(&mut self, remote:Data) {
+        // Longest common prefix of the two histories. Both sides only truly
+        // diverged when each carries entries the other lacks; on a single device
+        // the remote we just read back is an ancestor of the local data, so there
+        // is nothing to reconcile and re-running the merge would only disturb
+        // fields like debt that are not recomputed from history.
+        let mut prefix = 0;
+        while prefix < self.data.history.len() && prefix < remote.history.len() && self.data.history[prefix] == remote.history[prefix] {
+            prefix += 1;
         }
-        if self.data.history == old_data_updated.history && self.data.total_balance() == old_data_updated.total_balance() {
-            // updated cringe or something else, hope OK
-            return true;
+        if prefix < self.data.history.len() && prefix < remote.history.len() {
+            let (merged, report) = self.data.merge(&remote);
+            report.print();
+            self.data = merged;
+        } else if prefix == self.data.history.len() && prefix < remote.history.len() {
+            // Local is an ancestor of remote: fast-forward to the remote edits.
+            self.data = remote;
         }
-        println!("Unknown verifcation failure: {:?} vs {:?}", &old_data_updated, &self.data);
-        return false;
     }
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A timestamp exactly `days` whole days before now, in epoch milliseconds.
+    fn days_ago(days:i64) -> u64 {
+        return (Local::now() - Duration::days(days)).timestamp_millis() as u64;
+    }
+
+    /// A month-long gap must back-fill one charge per elapsed period, not a single
+    /// catch-up entry.
+    #[test]
+    fn update_backfills_every_missed_period() {
+        let mut data = Data::new();
+        data.history.clear();
+        data.balance = 100.;
+        data.rate = Some(0.);
+        data.last_updated = days_ago(35);
+        data.recurring = vec![RecurringItem{amount:10., reason:"rent".to_string(), period_days:7, last_applied:days_ago(35)}];
+
+        data.update(&0.);
+
+        // 35 days at a 7-day period is five occurrences.
+        assert_eq!(data.history.len(), 5);
+        assert!((data.balance - 50.).abs() < 1e-3);
+    }
+
+    /// Running `update` a second time with no further time elapsed must not
+    /// re-apply the charges it already back-filled.
+    #[test]
+    fn update_is_idempotent_on_repeat() {
+        let mut data = Data::new();
+        data.history.clear();
+        data.balance = 100.;
+        data.rate = Some(0.);
+        data.last_updated = days_ago(35);
+        data.recurring = vec![RecurringItem{amount:10., reason:"rent".to_string(), period_days:7, last_applied:days_ago(35)}];
+
+        data.update(&0.);
+        let history_len = data.history.len();
+        let balance = data.balance;
+
+        data.update(&0.);
+
+        assert_eq!(data.history.len(), history_len);
+        assert!((data.balance - balance).abs() < 1e-3);
+    }
+
+    /// A three-way merge keeps the shared prefix, interleaves the divergent
+    /// suffixes by time, and recomputes the balance from before the split.
+    #[test]
+    fn merge_combines_divergent_suffixes() {
+        let mut local = Data::new();
+        local.history.clear();
+        local.history.push(HistoryItem{amount:5., reason:"shared".to_string(), specific:None, time:1000});
+        let mut remote = local.clone();
+
+        local.history.push(HistoryItem{amount:2., reason:"local".to_string(), specific:None, time:3000});
+        local.balance = 98.;
+        remote.history.push(HistoryItem{amount:4., reason:"remote".to_string(), specific:None, time:2000});
+        remote.balance = 96.;
+        remote.last_updated = local.last_updated + 1;
+
+        let (merged, _report) = local.merge(&remote);
+
+        assert_eq!(merged.history.len(), 3);
+        assert_eq!(merged.history[1].reason, "remote");
+        assert_eq!(merged.history[2].reason, "local");
+        // 98 (local) + 2 (local suffix) back to the split, minus both suffixes.
+        assert!((merged.balance - 94.).abs() < 1e-3);
+    }
+
+    /// Debt is taken from the more recently updated side, so a repayment on either
+    /// device is never resurrected by the merge.
+    #[test]
+    fn merge_takes_debt_from_most_recent_side() {
+        let mut local = Data::new();
+        local.history.clear();
+        local.debt = 50.;
+        local.last_updated = 1000;
+        local.history.push(HistoryItem{amount:1., reason:"local".to_string(), specific:None, time:10});
+        let mut remote = local.clone();
+        remote.debt = 0.;
+        remote.last_updated = 2000;
+        remote.history[0] = HistoryItem{amount:1., reason:"remote".to_string(), specific:None, time:20};
+
+        let (merged, _report) = local.merge(&remote);
+
+        assert!(merged.debt.abs() < 1e-3);
+    }
+}
@@ -1,13 +1,13 @@
-use rusoto_core::{Region,credential::StaticProvider};
-use rusoto_s3::{S3, S3Client, CreateBucketRequest, PutObjectRequest, GetObjectRequest};
 use async_trait::async_trait;
 use rand::{thread_rng, Rng};
 use rand::distributions::Alphanumeric;
-use tokio::{io::AsyncReadExt};
+use object_store::{ObjectStore, aws::{AmazonS3, AmazonS3Builder}, azure::{MicrosoftAzure, MicrosoftAzureBuilder}, gcp::{GoogleCloudStorage, GoogleCloudStorageBuilder}, path::Path as ObjectPath, signer::Signer};
+use reqwest::Method;
 use serde::{Deserialize, Serialize};
 use std::path::{Path, PathBuf};
 use std::fs;
 use std::rc::Rc;
+use std::time::Duration;
 
 use crate::data::{Data};
 
@@ -15,6 +15,13 @@ use crate::data::{Data};
 pub trait DataProvider {
     async fn get(&self) -> Option<Data>;
     async fn put(&self,data:&Data);
+    /// Produce a time-limited presigned GET URL for the stored `data.json` so a
+    /// read-only snapshot can be shared without handing over credentials. Only
+    /// object stores that support presigning implement this; the default
+    /// rejects it.
+    async fn presign(&self, _expiry:Duration) -> Result<String, String> {
+        return Err("presigning not supported for this storage backend (S3 only)".to_string());
+    }
 }
 
 // serializable configuration that can produce a data provider
@@ -22,6 +29,39 @@ pub trait DataProviderFactory {
     fn to_provider(&self) -> Rc<dyn DataProvider>;
 }
 
+/// Parse a data-location URI and return the backend it names. The scheme
+/// selects the filesystem kind: `file:///home/me/budget` for local storage or
+/// `s3://bucket/prefix` for an S3-compatible object store. The URI only carries
+/// the bucket and object prefix; credentials, region, endpoint and profile come
+/// from `aws_factory` (the configured AWS backend) so a location URI composes
+/// with a custom endpoint rather than silently falling back to real AWS.
+pub fn provider_from_uri(uri:&str, aws_factory:&AwsS3DataProviderFactory) -> Result<Rc<dyn DataProvider>, String> {
+    let (scheme, rest) = match uri.split_once("://") {
+        Some(parts) => parts,
+        None => return Err(format!("invalid location `{}` (expected `scheme://...`)", uri)),
+    };
+    match scheme {
+        "file" => {
+            return Ok(Rc::new(LocalDataProvider{file_path:PathBuf::from(rest)}));
+        },
+        "s3" => {
+            let (bucket, prefix) = match rest.split_once('/') {
+                Some((bucket, prefix)) => (bucket.to_string(), prefix.to_string()),
+                None => (rest.to_string(), String::new()),
+            };
+            // Reuse the configured credentials/region/endpoint/profile, overriding
+            // only the bucket and object prefix the URI names.
+            let mut factory = aws_factory.clone();
+            factory.bucket_name = bucket;
+            // Nest the data object under the URI's path so `s3://bucket/prefix` is honored.
+            return Ok(Rc::new(AwsS3DataProvider{store:factory.build_store(), key:data_object_path(&prefix)}));
+        },
+        other => {
+            return Err(format!("unknown filesystem kind `{}` (expected `s3` or `file`)", other));
+        },
+    }
+}
+
 #[derive(Serialize, Deserialize, PartialEq, Clone)]
 pub struct LocalDataProvider {
     pub file_path:PathBuf
@@ -31,8 +71,8 @@ pub struct LocalDataProvider {
 impl DataProvider for LocalDataProvider {
     async fn get(&self) -> Option<Data> {
         if self.full_path().exists() {
-            let data:Data = serde_json::from_str(&fs::read_to_string(&self.full_path()).unwrap()).unwrap();
-            return Some(data);
+            let value:serde_json::Value = serde_json::from_str(&fs::read_to_string(&self.full_path()).unwrap()).unwrap();
+            return Some(crate::migrations::load(value));
         } else {
             return None;
         }
@@ -77,57 +117,62 @@ impl LocalDataProvider {
     }
 }
 
+/// The object key holding the budget data, below any configured prefix.
+const DATA_OBJECT:&str = "data.json";
+
+/// Build the object key for the `data.json` blob, nested under `prefix` when one
+/// was supplied in the location URI.
+fn data_object_path(prefix:&str) -> ObjectPath {
+    let prefix = prefix.trim_matches('/');
+    if prefix.is_empty() {
+        return ObjectPath::from(DATA_OBJECT);
+    }
+    return ObjectPath::from(format!("{}/{}", prefix, DATA_OBJECT));
+}
+
+/// Read and migrate the data object from any object store, returning `None` when
+/// it is missing.
+async fn object_store_get(store:&dyn ObjectStore, key:&ObjectPath) -> Option<Data> {
+    let result = store.get(key).await;
+    if result.is_err() {
+        return None;
+    } else {
+        let bytes = result.unwrap().bytes().await.unwrap();
+        let value:serde_json::Value = serde_json::from_slice(&bytes).unwrap();
+        return Some(crate::migrations::load(value));
+    }
+}
+
+/// Write the serialized `Data` to the data object on any object store.
+async fn object_store_put(store:&dyn ObjectStore, key:&ObjectPath, data:&Data) {
+    let contents = serde_json::to_string(&data).unwrap().into_bytes();
+    store
+        .put(key, contents.into())
+        .await
+        .expect("Failed to put data object");
+}
+
 struct AwsS3DataProvider {
-    s3:S3Client,
-    bucket_name:String
+    store:AmazonS3,
+    key:ObjectPath
 }
 
 #[async_trait]
 impl DataProvider for AwsS3DataProvider {
     async fn get(&self) -> Option<Data> {
-        let get_obj_req = GetObjectRequest {
-            bucket: self.bucket_name.clone(),
-            key: "data.json".to_string(),
-            ..Default::default()
-        };
-        let result = self.s3.get_object(get_obj_req).await;
-        if result.is_err() {
-            return None;
-        } else {
-            let stream = result.unwrap().body.unwrap();
-            let mut buffer = String::new();
-            stream.into_async_read().read_to_string(&mut buffer).await.unwrap();
-            let data:Data = serde_json::from_str(&buffer).unwrap();
-            return Some(data);
-        }
+        return object_store_get(&self.store, &self.key).await;
     }
 
     async fn put(&self, data:&Data) {
-        self.create_bucket().await;
-        let contents:Vec<u8> = serde_json::to_string(&data).unwrap().as_bytes().to_vec();
-        let put_request = PutObjectRequest {
-            bucket: self.bucket_name.to_owned(),
-            key: "data.json".to_string(),
-            body: Some(contents.into()),
-            ..Default::default()
-        };
-        self.s3
-            .put_object(put_request)
-            .await
-            .expect("Failed to put data object");
+        object_store_put(&self.store, &self.key, data).await;
     }
-}
 
-impl AwsS3DataProvider {
-    async fn create_bucket(&self) {
-        let create_bucket_req = CreateBucketRequest {
-            bucket: self.bucket_name.clone(),
-            ..Default::default()
-        };
-        self.s3
-            .create_bucket(create_bucket_req)
+    async fn presign(&self, expiry:Duration) -> Result<String, String> {
+        return self.store
+            .signed_url(Method::GET, &self.key, expiry)
             .await
-            .expect("Failed to create test bucket");
+            .map(|url| url.to_string())
+            .map_err(|err| format!("Failed to presign data object: {}", err));
     }
 }
 
@@ -136,24 +181,103 @@ pub struct AwsS3DataProviderFactory {
     pub access_key:String,
     pub secret_access_key:String,
     pub bucket_name:String,
-    pub region:Region,
+    /// AWS region name (e.g. `us-east-1`). Stored as a plain string so the
+    /// serialized config is stable and independent of any SDK's `Region` type.
+    #[serde(default = "default_region", deserialize_with = "deserialize_region")]
+    pub region:String,
+    /// Custom endpoint URL for S3-compatible stores (MinIO, Garage, Backblaze B2).
+    /// When set, the store talks to the self-hosted endpoint instead of real AWS.
+    #[serde(default)]
+    pub endpoint:Option<String>,
+    /// Named profile in the shared credentials file (`~/.aws/credentials`) to use
+    /// when no static keys are stored. Defaults to `default` when the file is
+    /// consulted without an explicit profile.
+    #[serde(default)]
+    pub profile:Option<String>,
+}
+
+/// The credential source chosen by the resolution chain, in priority order:
+/// explicit static keys, then environment variables, then the shared
+/// credentials file, then the EC2/container instance metadata service.
+enum CredentialSource {
+    Static{access_key:String, secret_access_key:String},
+    Profile(String),
+    InstanceMetadata,
+}
+
+fn default_region() -> String {
+    return "us-east-1".to_string();
+}
+
+/// Accept both the new plain-string region and the old rusoto `Region` enum
+/// representation (a bare variant name or a `{name, endpoint}` map) from
+/// pre-migration `config.json` files.
+fn deserialize_region<'de, D>(deserializer:D) -> Result<String, D::Error>
+where
+    D: serde::Deserializer<'de>,
+{
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum RegionCompat {
+        Name(String),
+        Custom{name:String},
+    }
+    return Ok(match RegionCompat::deserialize(deserializer)? {
+        RegionCompat::Name(name) => name,
+        RegionCompat::Custom{name} => name,
+    });
 }
 
 impl DataProviderFactory for AwsS3DataProviderFactory {
     fn to_provider(&self) -> Rc<dyn DataProvider> {
-        return Rc::new(AwsS3DataProvider{bucket_name:self.bucket_name.clone(), 
-            s3:S3Client::new_with(
-                rusoto_core::request::HttpClient::new().expect("Failed to create HTTP client"),
-                StaticProvider::new(self.access_key.clone(), self.secret_access_key.clone(), None, None),
-                self.region.clone(),
-            )}
-        );
+        return Rc::new(AwsS3DataProvider{store:self.build_store(), key:ObjectPath::from(DATA_OBJECT)});
     }
 }
 
 impl AwsS3DataProviderFactory {
     pub fn new() -> AwsS3DataProviderFactory {
-        return AwsS3DataProviderFactory{access_key:"".to_string(), secret_access_key:"".to_string(), bucket_name:AwsS3DataProviderFactory::generate_bucket_name(), region:Region::UsEast1}
+        return AwsS3DataProviderFactory{access_key:"".to_string(), secret_access_key:"".to_string(), bucket_name:AwsS3DataProviderFactory::generate_bucket_name(), region:default_region(), endpoint:None, profile:None}
+    }
+
+    /// Build the underlying S3 client, resolving credentials through the chain
+    /// and applying any custom endpoint.
+    fn build_store(&self) -> AmazonS3 {
+        let mut builder = AmazonS3Builder::new()
+            .with_bucket_name(self.bucket_name.clone())
+            .with_region(self.region.clone());
+        match self.resolve_credentials() {
+            CredentialSource::Static{access_key, secret_access_key} => {
+                builder = builder.with_access_key_id(access_key).with_secret_access_key(secret_access_key);
+            },
+            CredentialSource::Profile(profile) => {
+                builder = builder.with_profile(profile);
+            },
+            // No static keys: the builder falls through to the instance metadata service.
+            CredentialSource::InstanceMetadata => {},
+        }
+        if let Some(endpoint) = &self.endpoint {
+            // Self-hosted stores generally need path-style addressing and may be plain HTTP.
+            builder = builder.with_endpoint(endpoint.clone()).with_virtual_hosted_style_request(false).with_allow_http(true);
+        }
+        return builder.build().expect("Failed to build S3 client");
+    }
+
+    /// Walk the credential chain and return the first source that yields usable
+    /// credentials, so users never have to paste secrets into `config.json`.
+    fn resolve_credentials(&self) -> CredentialSource {
+        if !self.access_key.is_empty() && !self.secret_access_key.is_empty() {
+            return CredentialSource::Static{access_key:self.access_key.clone(), secret_access_key:self.secret_access_key.clone()};
+        }
+        if let (Ok(access_key), Ok(secret_access_key)) = (std::env::var("AWS_ACCESS_KEY_ID"), std::env::var("AWS_SECRET_ACCESS_KEY")) {
+            if !access_key.is_empty() && !secret_access_key.is_empty() {
+                return CredentialSource::Static{access_key, secret_access_key};
+            }
+        }
+        let credentials_file = dirs::home_dir().map(|home| home.join(".aws").join("credentials"));
+        if self.profile.is_some() || credentials_file.map(|path| path.exists()).unwrap_or(false) {
+            return CredentialSource::Profile(self.profile.clone().unwrap_or_else(|| "default".to_string()));
+        }
+        return CredentialSource::InstanceMetadata;
     }
 
     fn generate_bucket_name() -> String {
@@ -164,4 +288,88 @@ impl AwsS3DataProviderFactory {
         .collect();
         return format!("bucket-{}", rand_string.to_lowercase())
     }
+}
+
+struct GoogleCloudStorageDataProvider {
+    store:GoogleCloudStorage,
+    key:ObjectPath
+}
+
+#[async_trait]
+impl DataProvider for GoogleCloudStorageDataProvider {
+    async fn get(&self) -> Option<Data> {
+        return object_store_get(&self.store, &self.key).await;
+    }
+
+    async fn put(&self, data:&Data) {
+        object_store_put(&self.store, &self.key, data).await;
+    }
+}
+
+#[derive(Serialize, Deserialize, PartialEq, Clone)]
+pub struct GoogleCloudStorageDataProviderFactory {
+    pub bucket_name:String,
+    /// Path to the service-account JSON key used to authenticate. When unset,
+    /// the builder falls back to application default credentials.
+    #[serde(default)]
+    pub service_account:Option<String>,
+}
+
+impl DataProviderFactory for GoogleCloudStorageDataProviderFactory {
+    fn to_provider(&self) -> Rc<dyn DataProvider> {
+        let mut builder = GoogleCloudStorageBuilder::new().with_bucket_name(self.bucket_name.clone());
+        if let Some(service_account) = &self.service_account {
+            builder = builder.with_service_account_path(service_account.clone());
+        }
+        let store = builder.build().expect("Failed to build GCS client");
+        return Rc::new(GoogleCloudStorageDataProvider{store, key:ObjectPath::from(DATA_OBJECT)});
+    }
+}
+
+impl GoogleCloudStorageDataProviderFactory {
+    pub fn new() -> GoogleCloudStorageDataProviderFactory {
+        return GoogleCloudStorageDataProviderFactory{bucket_name:AwsS3DataProviderFactory::generate_bucket_name(), service_account:None}
+    }
+}
+
+struct AzureBlobDataProvider {
+    store:MicrosoftAzure,
+    key:ObjectPath
+}
+
+#[async_trait]
+impl DataProvider for AzureBlobDataProvider {
+    async fn get(&self) -> Option<Data> {
+        return object_store_get(&self.store, &self.key).await;
+    }
+
+    async fn put(&self, data:&Data) {
+        object_store_put(&self.store, &self.key, data).await;
+    }
+}
+
+#[derive(Serialize, Deserialize, PartialEq, Clone)]
+pub struct AzureBlobDataProviderFactory {
+    /// Blob container holding the single `data.json` object.
+    pub container_name:String,
+    /// Storage-account connection string carrying account name and key.
+    #[serde(default)]
+    pub connection_string:String,
+}
+
+impl DataProviderFactory for AzureBlobDataProviderFactory {
+    fn to_provider(&self) -> Rc<dyn DataProvider> {
+        let store = MicrosoftAzureBuilder::new()
+            .with_container_name(self.container_name.clone())
+            .with_connection_string(self.connection_string.clone())
+            .build()
+            .expect("Failed to build Azure client");
+        return Rc::new(AzureBlobDataProvider{store, key:ObjectPath::from(DATA_OBJECT)});
+    }
+}
+
+impl AzureBlobDataProviderFactory {
+    pub fn new() -> AzureBlobDataProviderFactory {
+        return AzureBlobDataProviderFactory{container_name:AwsS3DataProviderFactory::generate_bucket_name(), connection_string:"".to_string()}
+    }
 }
\ No newline at end of file
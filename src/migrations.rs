@@ -0,0 +1,45 @@
+use serde_json::Value;
+
+use crate::data::Data;
+
+/// The current on-disk data schema version, derived from the crate major
+/// version just like [`crate::DATA_VERSION`].
+fn current_version() -> u64 {
+    return *crate::DATA_VERSION as u64;
+}
+
+/// Return the migration transforming a serialized `Data` payload from `version`
+/// to `version + 1`, or `None` when no migration is registered for that step.
+///
+/// Each release that changes the schema appends exactly one arm here, e.g.
+/// `1 => Some(migrate_1_to_2)`, so the chain below runs them in order.
+fn migration_for(version:u64) -> Option<fn(Value) -> Value> {
+    // No schema migrations are registered yet. Each release that changes the
+    // schema appends one arm to this registry, e.g. `1 => Some(migrate_1_to_2)`.
+    let _ = version;
+    return None;
+}
+
+/// Bring a serialized `Data` payload up to the current schema version by
+/// running the ordered chain of registered migrations, then deserialize it.
+///
+/// Old files that predate the `version` field are treated as version 0, so the
+/// full chain applies. Files already at (or above) the current version are
+/// deserialized directly.
+pub fn load(value:Value) -> Data {
+    let mut value = value;
+    let mut version = value.get("version").and_then(|v| v.as_f64()).map(|v| v as u64).unwrap_or(0);
+    while version < current_version() {
+        match migration_for(version) {
+            Some(migrate) => {
+                value = migrate(value);
+            },
+            None => break,
+        }
+        version += 1;
+        if let Some(object) = value.as_object_mut() {
+            object.insert("version".to_string(), serde_json::json!(version as f32));
+        }
+    }
+    return serde_json::from_value(value).unwrap();
+}